@@ -0,0 +1,90 @@
+//! Scaling benchmark for the RS shard-preparation stage of `encode_payload` across thread
+//! counts, plus a single-point baseline for `embed_in_pdf`.
+//!
+//! `embed_in_pdf` has no thread-count axis here because it has no worker pool to scale: its
+//! content stream is identical across pages and built once, and the per-page step that does
+//! vary mutates a `Document`, which isn't `Sync` (see `embed_in_pdf`'s doc comment). Per-page
+//! PDF parallelism was evaluated and rejected for the current carrier format, not silently
+//! dropped; `bench_pdf_embed_baseline` exists to catch regressions in the sequential path, not
+//! to demonstrate scaling.
+//!
+//! Run with `cargo bench --bench pdf_embed`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lopdf::{Dictionary, Document, Object};
+use mirseo_updrm::{embed_in_pdf, encode_payload, Codec, Redundancy};
+
+const PAGE_COUNT: usize = 300;
+
+fn build_test_pdf() -> Vec<u8> {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+
+    let mut kids = Vec::with_capacity(PAGE_COUNT);
+    for _ in 0..PAGE_COUNT {
+        let page_id = doc.add_object(Dictionary::from_iter(vec![
+            (b"Type".to_vec(), Object::Name(b"Page".to_vec())),
+            (b"Parent".to_vec(), Object::Reference(pages_id)),
+        ]));
+        kids.push(Object::Reference(page_id));
+    }
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(Dictionary::from_iter(vec![
+            (b"Type".to_vec(), Object::Name(b"Pages".to_vec())),
+            (b"Count".to_vec(), Object::Integer(PAGE_COUNT as i64)),
+            (b"Kids".to_vec(), Object::Array(kids)),
+        ])),
+    );
+
+    let catalog_id = doc.add_object(Dictionary::from_iter(vec![
+        (b"Type".to_vec(), Object::Name(b"Catalog".to_vec())),
+        (b"Pages".to_vec(), Object::Reference(pages_id)),
+    ]));
+    doc.trailer.set("Root", catalog_id);
+
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).expect("build fixture PDF");
+    bytes
+}
+
+fn bench_pdf_embed_baseline(c: &mut Criterion) {
+    let carrier = build_test_pdf();
+    let payload = vec![0xAB; 4096];
+
+    c.bench_function("embed_in_pdf/300_pages_sequential", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            embed_in_pdf(carrier.as_slice(), &payload, &mut out).unwrap();
+        });
+    });
+}
+
+fn bench_shard_prep(c: &mut Criterion) {
+    let raw_data = vec![0x5A; 256 * 1024];
+
+    // Every iteration reuses the same cached pool for a given `threads` value (see
+    // `shard_pool_for`), so this measures steady-state scaling rather than pool-spin-up cost.
+    let mut group = c.benchmark_group("encode_payload");
+    for threads in [1, 4, 0] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(if threads == 0 {
+                "all_cores".to_string()
+            } else {
+                threads.to_string()
+            }),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    encode_payload(raw_data.clone(), Codec::None, Redundancy::default(), threads)
+                        .unwrap();
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_pdf_embed_baseline, bench_shard_prep);
+criterion_main!(benches);
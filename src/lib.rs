@@ -5,13 +5,289 @@ use lopdf::{
 };
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
+use rayon::prelude::*;
 use reed_solomon_erasure::{galois_8, Error as RSError, ReedSolomon};
+use std::collections::HashMap;
 use std::fs;
-use std::io::Cursor;
+use std::io::{Cursor, Read, Write};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+
+/// Magic bytes identifying a nano-drm container.
+const CONTAINER_MAGIC: &[u8; 4] = b"UDRM";
+/// Current container format version. Bump when the header layout changes and keep
+/// `ContainerHeader::from_bytes` able to reject versions it can't parse.
+const CONTAINER_VERSION: u8 = 1;
+
+/// Fixed-size, self-describing header prepended to every embedded payload.
+///
+/// Storing the Reed-Solomon shard counts and a CRC32 of the plaintext here means `read` no
+/// longer has to assume the writer's constants, and corrupted or truncated extractions are
+/// caught before (and after) RS reconstruction instead of silently producing garbage.
+struct ContainerHeader {
+    version: u8,
+    flags: u8,
+    data_shards: u16,
+    parity_shards: u16,
+    plaintext_len: u64,
+    crc32: u32,
+}
+
+impl ContainerHeader {
+    const SIZE: usize = 4 + 1 + 1 + 2 + 2 + 8 + 4;
+
+    fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(CONTAINER_MAGIC);
+        buf[4] = self.version;
+        buf[5] = self.flags;
+        buf[6..8].copy_from_slice(&self.data_shards.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.parity_shards.to_be_bytes());
+        buf[10..18].copy_from_slice(&self.plaintext_len.to_be_bytes());
+        buf[18..22].copy_from_slice(&self.crc32.to_be_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Result<Self, DrmError> {
+        if buf.len() < Self::SIZE {
+            return Err(DrmError::Message(
+                "Container too short to contain a header".to_string(),
+            ));
+        }
+        if &buf[0..4] != CONTAINER_MAGIC {
+            return Err(DrmError::Message(
+                "Not a nano-drm container: bad magic bytes".to_string(),
+            ));
+        }
+        let version = buf[4];
+        if version != CONTAINER_VERSION {
+            return Err(DrmError::Message(format!(
+                "Unsupported container format version: {}",
+                version
+            )));
+        }
+        Ok(ContainerHeader {
+            version,
+            flags: buf[5],
+            data_shards: u16::from_be_bytes([buf[6], buf[7]]),
+            parity_shards: u16::from_be_bytes([buf[8], buf[9]]),
+            plaintext_len: u64::from_be_bytes(buf[10..18].try_into().unwrap()),
+            crc32: u32::from_be_bytes(buf[18..22].try_into().unwrap()),
+        })
+    }
+}
+
+fn crc32_of(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Mask over `ContainerHeader::flags` selecting the compression codec bits.
+const FLAG_CODEC_MASK: u8 = 0b0000_0011;
+
+/// Compression codec applied to the plaintext before Reed-Solomon encoding. Each non-`None`
+/// variant is backed by an optional Cargo feature, mirroring nod-rs's `compress-zstd` gating,
+/// so builds that don't need a given codec don't have to pull in its dependency.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Deflate,
+    Zstd,
+}
+
+impl Codec {
+    pub fn parse(name: Option<&str>) -> Result<Self, DrmError> {
+        match name.unwrap_or("none") {
+            "none" => Ok(Codec::None),
+            "deflate" => Ok(Codec::Deflate),
+            "zstd" => Ok(Codec::Zstd),
+            other => Err(DrmError::Message(format!(
+                "Unknown compression codec: {other} (expected \"none\", \"deflate\" or \"zstd\")"
+            ))),
+        }
+    }
+
+    fn flag_bits(self) -> u8 {
+        match self {
+            Codec::None => 0b00,
+            Codec::Deflate => 0b01,
+            Codec::Zstd => 0b10,
+        }
+    }
+
+    fn from_flags(flags: u8) -> Result<Self, DrmError> {
+        match flags & FLAG_CODEC_MASK {
+            0b00 => Ok(Codec::None),
+            0b01 => Ok(Codec::Deflate),
+            0b10 => Ok(Codec::Zstd),
+            other => Err(DrmError::Message(format!(
+                "Container header has an unrecognized codec flag: {other:#04b}"
+            ))),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>, DrmError> {
+        match self {
+            Codec::None => Ok(data.to_vec()),
+            Codec::Deflate => compress_deflate(data),
+            Codec::Zstd => compress_zstd(data),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>, DrmError> {
+        match self {
+            Codec::None => Ok(data.to_vec()),
+            Codec::Deflate => decompress_deflate(data),
+            Codec::Zstd => decompress_zstd(data),
+        }
+    }
+}
+
+/// Reed-Solomon shard counts for `encode_payload`, either picked by name or given explicitly.
+/// Since the header (see `ContainerHeader`) now carries whatever counts the writer chose,
+/// `read`/`decode_payload` never need to know which profile produced a given container.
+#[derive(Clone, Copy)]
+pub struct Redundancy {
+    pub data_shards: u16,
+    pub parity_shards: u16,
+}
+
+impl Redundancy {
+    /// Favors capacity: small carriers (a single still PNG, a short JSON watermark) that
+    /// don't expect lossy re-encoding or heavy editing downstream.
+    const LIGHT: Redundancy = Redundancy {
+        data_shards: 16,
+        parity_shards: 2,
+    };
+    /// The ratio `encode_payload` used before this became configurable; kept as the default.
+    const STANDARD: Redundancy = Redundancy {
+        data_shards: 10,
+        parity_shards: 4,
+    };
+    /// Favors resilience over capacity: lossy-ish or heavily-edited delivery paths (e.g. a
+    /// JPEG re-encode, a screenshot-and-recompress round trip).
+    const PARANOID: Redundancy = Redundancy {
+        data_shards: 6,
+        parity_shards: 10,
+    };
+
+    /// Parses the `redundancy` argument to `write`/`write_bytes`: either a named profile
+    /// (`"light"`, `"standard"`, `"paranoid"`) or an explicit `(data_shards, parity_shards)`
+    /// pair, mirroring how `Codec::parse` takes a name for the compression argument.
+    fn parse(value: Option<&Bound<'_, PyAny>>) -> Result<Self, DrmError> {
+        let redundancy = match value {
+            None => Self::STANDARD,
+            Some(v) => {
+                if let Ok(name) = v.extract::<String>() {
+                    match name.as_str() {
+                        "light" => Self::LIGHT,
+                        "standard" => Self::STANDARD,
+                        "paranoid" => Self::PARANOID,
+                        other => {
+                            return Err(DrmError::Message(format!(
+                                "Unknown redundancy profile: {other} (expected \"light\", \"standard\", \"paranoid\", or a (data_shards, parity_shards) pair)"
+                            )))
+                        }
+                    }
+                } else if let Ok((data_shards, parity_shards)) = v.extract::<(u16, u16)>() {
+                    Redundancy {
+                        data_shards,
+                        parity_shards,
+                    }
+                } else {
+                    return Err(DrmError::Py(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                        "redundancy must be a profile name (str) or a (data_shards, parity_shards) pair"
+                            .to_string(),
+                    )));
+                }
+            }
+        };
+        redundancy.validate()?;
+        Ok(redundancy)
+    }
+
+    fn validate(self) -> Result<(), DrmError> {
+        if self.data_shards == 0 {
+            return Err(DrmError::Message(
+                "redundancy data_shards must be at least 1".to_string(),
+            ));
+        }
+        let total = self.data_shards as usize + self.parity_shards as usize;
+        if total > 255 {
+            return Err(DrmError::Message(format!(
+                "redundancy data_shards + parity_shards must be <= 255 (the galois_8 field limit), got {total}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Redundancy {
+    /// The ratio `encode_payload` used before this became configurable.
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+#[cfg(feature = "compress-deflate")]
+fn compress_deflate(data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    use flate2::{write::DeflateEncoder, Compression};
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).map_err(DrmError::Io)?;
+    encoder.finish().map_err(DrmError::Io)
+}
+
+#[cfg(not(feature = "compress-deflate"))]
+fn compress_deflate(_data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    Err(DrmError::Message(
+        "This build was compiled without the \"compress-deflate\" feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "compress-deflate")]
+fn decompress_deflate(data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    use flate2::read::DeflateDecoder;
+    let mut decoder = DeflateDecoder::new(data);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).map_err(DrmError::Io)?;
+    Ok(out)
+}
+
+#[cfg(not(feature = "compress-deflate"))]
+fn decompress_deflate(_data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    Err(DrmError::Message(
+        "This build was compiled without the \"compress-deflate\" feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "compress-zstd")]
+fn compress_zstd(data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    zstd::stream::encode_all(data, 0).map_err(DrmError::Io)
+}
+
+#[cfg(not(feature = "compress-zstd"))]
+fn compress_zstd(_data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    Err(DrmError::Message(
+        "This build was compiled without the \"compress-zstd\" feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "compress-zstd")]
+fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    zstd::stream::decode_all(data).map_err(DrmError::Io)
+}
+
+#[cfg(not(feature = "compress-zstd"))]
+fn decompress_zstd(_data: &[u8]) -> Result<Vec<u8>, DrmError> {
+    Err(DrmError::Message(
+        "This build was compiled without the \"compress-zstd\" feature".to_string(),
+    ))
+}
 
 // ... (DrmError, From impls, FileType, detect_file_type are unchanged) ...
 #[derive(Debug)]
-enum DrmError {
+pub enum DrmError {
     Io(std::io::Error),
     Py(PyErr),
     Rs(RSError),
@@ -45,6 +321,11 @@ impl From<lopdf::Error> for DrmError {
         DrmError::Pdf(err)
     }
 }
+impl From<rayon::ThreadPoolBuildError> for DrmError {
+    fn from(err: rayon::ThreadPoolBuildError) -> DrmError {
+        DrmError::Message(format!("Failed to start worker pool: {}", err))
+    }
+}
 
 impl From<DrmError> for PyErr {
     fn from(err: DrmError) -> PyErr {
@@ -71,6 +352,7 @@ impl From<DrmError> for PyErr {
 enum FileType {
     Png,
     Pdf,
+    Jpeg,
     Unsupported,
 }
 
@@ -79,13 +361,26 @@ fn detect_file_type(data: &[u8]) -> FileType {
         FileType::Png
     } else if data.len() > 4 && &data[0..4] == b"%PDF" {
         FileType::Pdf
+    } else if data.len() > 2 && &data[0..2] == b"\xFF\xD8" {
+        FileType::Jpeg
     } else {
         FileType::Unsupported
     }
 }
 
-fn embed_in_png(image_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmError> {
-    let mut img = image::load_from_memory(image_data)?.to_rgba8();
+fn embed_in_png<R: Read, W: Write>(
+    mut carrier: R,
+    data_to_embed: &[u8],
+    mut out: W,
+) -> Result<(), DrmError> {
+    let mut image_data = Vec::new();
+    carrier.read_to_end(&mut image_data).map_err(DrmError::Io)?;
+
+    if is_apng(&image_data) {
+        return embed_in_apng(&image_data[..], data_to_embed, out);
+    }
+
+    let mut img = image::load_from_memory(&image_data)?.to_rgba8();
     let capacity = img.as_raw().len();
     let required_capacity = data_to_embed.len() * 8;
 
@@ -114,19 +409,27 @@ fn embed_in_png(image_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmE
         &mut Cursor::new(&mut result_bytes),
         image::ImageOutputFormat::Png,
     )?;
+    out.write_all(&result_bytes).map_err(DrmError::Io)?;
 
-    Ok(result_bytes)
+    Ok(())
 }
 
-fn extract_from_png(image_data: &[u8]) -> Result<Vec<u8>, DrmError> {
-    let img: ImageBuffer<Rgba<u8>, Vec<u8>> = image::load_from_memory(image_data)?.to_rgba8();
+fn extract_from_png<R: Read>(mut carrier: R) -> Result<Vec<u8>, DrmError> {
+    let mut image_data = Vec::new();
+    carrier.read_to_end(&mut image_data).map_err(DrmError::Io)?;
+
+    if is_apng(&image_data) {
+        return extract_from_apng(&image_data[..]);
+    }
+
+    let img: ImageBuffer<Rgba<u8>, Vec<u8>> = image::load_from_memory(&image_data)?.to_rgba8();
     let mut bit_iter = img.as_raw().iter().map(|byte| byte & 1);
 
     let mut len_bytes = [0u8; 8];
-    for i in 0..8 {
+    for byte in &mut len_bytes {
         for j in 0..8 {
             if let Some(bit) = bit_iter.next() {
-                len_bytes[i] |= bit << j;
+                *byte |= bit << j;
             } else {
                 return Err(DrmError::Message(
                     "Reached end of image before reading payload length".to_string(),
@@ -137,10 +440,10 @@ fn extract_from_png(image_data: &[u8]) -> Result<Vec<u8>, DrmError> {
     let payload_len = u64::from_be_bytes(len_bytes) as usize;
 
     let mut payload = vec![0u8; payload_len];
-    for i in 0..payload_len {
+    for byte in payload.iter_mut().take(payload_len) {
         for j in 0..8 {
             if let Some(bit) = bit_iter.next() {
-                payload[i] |= bit << j;
+                *byte |= bit << j;
             } else {
                 return Err(DrmError::Message(
                     "Reached end of image before reading full payload".to_string(),
@@ -152,8 +455,555 @@ fn extract_from_png(image_data: &[u8]) -> Result<Vec<u8>, DrmError> {
     Ok(payload)
 }
 
-fn extract_from_pdf(pdf_data: &[u8]) -> Result<Vec<u8>, DrmError> {
-    let doc = Document::load_mem(pdf_data)?;
+/// Bound on uncompressed APNG frames held in flight between the background decode thread and
+/// the embed/extract loop, so a carrier with hundreds of frames can't balloon memory.
+const APNG_FRAME_CHANNEL_BOUND: usize = 4;
+
+/// A single decoded APNG frame plus the `fcTL` delay, dispose and blend settings that are
+/// re-emitted with it. The frame's x/y offset is not kept: every frame is written at the
+/// position and size of the first frame.
+struct ApngFrame {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+    delay_num: u16,
+    delay_den: u16,
+    dispose_op: png::DisposeOp,
+    blend_op: png::BlendOp,
+}
+
+/// Cheap pre-check for whether a PNG carries an `acTL` chunk (i.e. is animated). Avoids paying
+/// for APNG frame decoding on the overwhelmingly common case of a plain still PNG.
+fn is_apng(png_data: &[u8]) -> bool {
+    png_data.windows(4).any(|w| w == b"acTL")
+}
+
+/// Decodes an APNG's frames on a background thread, feeding each one through a bounded
+/// channel as it becomes available (mirroring wezterm's decode-ahead pipeline) so callers can
+/// start consuming bits from frame 0 without the whole animation sitting in memory at once.
+/// Returns the frame count read from `acTL` up front, plus the receiving end of the channel.
+fn decode_apng_frames(png_data: Vec<u8>) -> Result<(u32, mpsc::Receiver<Result<ApngFrame, DrmError>>), DrmError> {
+    let header_reader = png::Decoder::new(Cursor::new(&png_data))
+        .read_info()
+        .map_err(|e| DrmError::Message(format!("Failed to read APNG header: {e}")))?;
+    let frame_count = header_reader
+        .info()
+        .animation_control()
+        .map(|ac| ac.num_frames)
+        .unwrap_or(1);
+    drop(header_reader);
+
+    let (tx, rx) = mpsc::sync_channel(APNG_FRAME_CHANNEL_BOUND);
+    thread::spawn(move || {
+        let outcome = (|| -> Result<(), DrmError> {
+            let mut reader = png::Decoder::new(Cursor::new(&png_data))
+                .read_info()
+                .map_err(|e| DrmError::Message(format!("Failed to read APNG header: {e}")))?;
+            let mut buf = vec![0u8; reader.output_buffer_size()];
+            for _ in 0..frame_count {
+                let frame_info = reader
+                    .next_frame(&mut buf)
+                    .map_err(|e| DrmError::Message(format!("Failed to decode APNG frame: {e}")))?;
+                let fc = reader.info().frame_control();
+                let frame = ApngFrame {
+                    rgba: buf[..frame_info.buffer_size()].to_vec(),
+                    width: frame_info.width,
+                    height: frame_info.height,
+                    delay_num: fc.map(|f| f.delay_num).unwrap_or(1),
+                    delay_den: fc.map(|f| f.delay_den).unwrap_or(30),
+                    dispose_op: fc.map(|f| f.dispose_op).unwrap_or(png::DisposeOp::None),
+                    blend_op: fc.map(|f| f.blend_op).unwrap_or(png::BlendOp::Source),
+                };
+                if tx.send(Ok(frame)).is_err() {
+                    // The consumer dropped the receiver (it already has everything it
+                    // needs) - stop decoding the remaining frames.
+                    return Ok(());
+                }
+            }
+            Ok(())
+        })();
+        if let Err(e) = outcome {
+            let _ = tx.send(Err(e));
+        }
+    });
+
+    Ok((frame_count, rx))
+}
+
+/// Embeds `data_to_embed` across an APNG's frames, treating the concatenation of every
+/// frame's RGBA buffer as one LSB bit-space. This multiplies usable capacity by the frame
+/// count over a single still image without changing the public `write`/`read` signatures.
+fn embed_in_apng<R: Read, W: Write>(
+    mut carrier: R,
+    data_to_embed: &[u8],
+    out: W,
+) -> Result<(), DrmError> {
+    let mut png_data = Vec::new();
+    carrier.read_to_end(&mut png_data).map_err(DrmError::Io)?;
+
+    let (frame_count, frames_rx) = decode_apng_frames(png_data)?;
+    let mut bit_iter = data_to_embed
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1));
+
+    let mut out_slot = Some(out);
+    let mut png_writer = None;
+    let mut frames_written = 0u32;
+    for message in frames_rx {
+        let mut frame = message?;
+        for byte in frame.rgba.iter_mut() {
+            match bit_iter.next() {
+                Some(bit) => *byte = (*byte & 0xFE) | bit,
+                None => break,
+            }
+        }
+
+        if png_writer.is_none() {
+            let mut encoder = png::Encoder::new(
+                out_slot.take().expect("out consumed more than once"),
+                frame.width,
+                frame.height,
+            );
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .set_animated(frame_count, 0)
+                .map_err(|e| DrmError::Message(format!("Failed to start APNG output: {e}")))?;
+            png_writer = Some(
+                encoder
+                    .write_header()
+                    .map_err(|e| DrmError::Message(format!("Failed to write APNG header: {e}")))?,
+            );
+        }
+        let writer = png_writer.as_mut().unwrap();
+        writer
+            .set_frame_delay(frame.delay_num, frame.delay_den)
+            .map_err(|e| DrmError::Message(format!("Failed to set APNG frame delay: {e}")))?;
+        writer
+            .set_dispose_op(frame.dispose_op)
+            .map_err(|e| DrmError::Message(format!("Failed to set APNG dispose op: {e}")))?;
+        writer
+            .set_blend_op(frame.blend_op)
+            .map_err(|e| DrmError::Message(format!("Failed to set APNG blend op: {e}")))?;
+        writer
+            .write_image_data(&frame.rgba)
+            .map_err(|e| DrmError::Message(format!("Failed to write APNG frame: {e}")))?;
+        frames_written += 1;
+    }
+
+    if bit_iter.next().is_some() {
+        return Err(DrmError::Message(format!(
+            "Not enough space across {frames_written} APNG frames for this payload"
+        )));
+    }
+
+    match png_writer {
+        Some(writer) => writer
+            .finish()
+            .map_err(|e| DrmError::Message(format!("Failed to finalize APNG: {e}"))),
+        None => Err(DrmError::Message("APNG carrier has no frames".to_string())),
+    }
+}
+
+/// Total bit count needed to hold an 8-byte big-endian length prefix followed by
+/// `payload_len` bytes of payload, computed with checked arithmetic: `payload_len` is read
+/// straight off carrier-controlled LSBs, so a corrupted or adversarial carrier can claim any
+/// `u64` value, and `payload_len * 8` (or `+ 64`) can silently wrap on overflow. A wrapped
+/// result would make a too-small "required bits" figure compare as already satisfied,
+/// skipping the capacity check that's supposed to reject the claim before `vec![0u8; ..]`
+/// turns it into a multi-exabyte allocation.
+fn required_bits_for_payload(payload_len: usize) -> Result<usize, DrmError> {
+    payload_len
+        .checked_mul(8)
+        .and_then(|bits| bits.checked_add(64))
+        .ok_or_else(|| {
+            DrmError::Message(
+                "Embedded payload length overflows while computing required bit capacity"
+                    .to_string(),
+            )
+        })
+}
+
+/// Extracts a payload spread across an APNG's frames by [`embed_in_apng`]. Stops decoding as
+/// soon as it has collected the length prefix plus the full payload, dropping the channel
+/// receiver so the background decode thread exits early on large animations.
+fn extract_from_apng<R: Read>(mut carrier: R) -> Result<Vec<u8>, DrmError> {
+    let mut png_data = Vec::new();
+    carrier.read_to_end(&mut png_data).map_err(DrmError::Io)?;
+
+    let (_frame_count, frames_rx) = decode_apng_frames(png_data)?;
+
+    let mut bits: Vec<u8> = Vec::new();
+    let mut required_bits: Option<usize> = None;
+
+    for message in frames_rx {
+        let frame = message?;
+        bits.extend(frame.rgba.iter().map(|byte| byte & 1));
+
+        if required_bits.is_none() && bits.len() >= 64 {
+            let mut len_bytes = [0u8; 8];
+            for i in 0..8 {
+                for j in 0..8 {
+                    len_bytes[i] |= bits[i * 8 + j] << j;
+                }
+            }
+            let payload_len = u64::from_be_bytes(len_bytes) as usize;
+            required_bits = Some(required_bits_for_payload(payload_len)?);
+        }
+        if let Some(required) = required_bits {
+            if bits.len() >= required {
+                break;
+            }
+        }
+    }
+
+    let required_bits = required_bits.ok_or_else(|| {
+        DrmError::Message("Reached end of APNG frames before reading payload length".to_string())
+    })?;
+    if bits.len() < required_bits {
+        return Err(DrmError::Message(
+            "Reached end of APNG frames before reading full payload".to_string(),
+        ));
+    }
+    let payload_len = (required_bits - 64) / 8;
+
+    let mut payload = vec![0u8; payload_len];
+    for i in 0..payload_len {
+        for j in 0..8 {
+            payload[i] |= bits[64 + i * 8 + j] << j;
+        }
+    }
+
+    Ok(payload)
+}
+
+/// An AC coefficient is "fragile" if perturbing its LSB would likely flip it to/from zero
+/// under re-quantization, which both changes the visible image more than intended and makes
+/// the bit unreliable to read back. 0 and 1 (and their negatives) are excluded for this reason.
+///
+/// `-2` is excluded too, for a different reason: this module decides eligibility by re-running
+/// this same predicate against the coefficient's *current* value, both when choosing which
+/// coefficients to write during embed and which to read during extract. Writing a bit clears a
+/// coefficient's LSB and ORs the bit back in, which leaves every other eligible value's
+/// exclusion status unchanged except `-2`'s — `-2 | 1` is `-1`, one of the values excluded
+/// above. Without this exclusion, a coefficient embed chose as eligible could read back as
+/// ineligible, desynchronizing the embed and extract bit streams for everything that follows.
+fn is_eligible_ac_coefficient(index_in_block: usize, coefficient: i16) -> bool {
+    index_in_block != 0
+        && coefficient != 0
+        && coefficient != 1
+        && coefficient != -1
+        && coefficient != -2
+}
+
+/// Raw bindings to libjpeg's virtual coefficient-array API.
+///
+/// The high-level `mozjpeg` crate only exposes raw sample/pixel decoding (`Decompress::raw`,
+/// which sets `raw_data_out`) — it has no accessor for the quantized DCT coefficients this
+/// module watermarks. Real coefficient access means `jpeg_read_coefficients`/
+/// `jpeg_write_coefficients` via `mozjpeg-sys`'s FFI, following the same pattern `jpegtran`
+/// itself uses: read coefficients, mutate blocks in place through the decompressor's own
+/// virtual-array memory manager, then hand the same arrays to a compressor that copies the
+/// source's critical parameters (quant tables, sampling factors).
+mod jpeg_coeff {
+    use super::DrmError;
+    use mozjpeg_sys as ffi;
+    use std::os::raw::{c_int, c_ulong, c_void};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    /// libjpeg reports fatal errors by invoking `error_exit`. mozjpeg-sys declares every entry
+    /// point "C-unwind", so unwinding a Rust panic through it (instead of the default
+    /// `longjmp`) is well-defined; we convert that panic back into a `DrmError` at the call site.
+    extern "C-unwind" fn unwind_on_error(cinfo: &mut ffi::jpeg_common_struct) {
+        std::panic::resume_unwind(Box::new(format_error_message(cinfo)));
+    }
+
+    extern "C-unwind" fn silence_trace(_cinfo: &mut ffi::jpeg_common_struct, _level: c_int) {}
+
+    fn format_error_message(cinfo: &mut ffi::jpeg_common_struct) -> String {
+        unsafe {
+            let err = &*cinfo.err;
+            match err.format_message {
+                // The binding's declared signature takes `&[u8; 80]`, but the underlying C
+                // function writes the message into it; retrieve it through the mutable
+                // signature libjpeg actually uses.
+                Some(fmt) => {
+                    let fmt: unsafe extern "C-unwind" fn(&mut ffi::jpeg_common_struct, &mut [u8; 80]) =
+                        std::mem::transmute(fmt);
+                    let mut buffer = [0u8; 80];
+                    fmt(cinfo, &mut buffer);
+                    let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+                    String::from_utf8_lossy(&buffer[..end]).into_owned()
+                }
+                None => format!("libjpeg error code {}", err.msg_code),
+            }
+        }
+    }
+
+    fn panic_to_err(panic: Box<dyn std::any::Any + Send>) -> DrmError {
+        let msg = panic
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown libjpeg error".to_string());
+        DrmError::Message(format!("JPEG coefficient access failed: {msg}"))
+    }
+
+    fn error_mgr() -> ffi::jpeg_error_mgr {
+        unsafe {
+            let mut err: ffi::jpeg_error_mgr = std::mem::zeroed();
+            ffi::jpeg_std_error(&mut err);
+            err.error_exit = Some(unwind_on_error);
+            err.emit_message = Some(silence_trace);
+            err
+        }
+    }
+
+    /// Visits every DCT coefficient of the luma (first) component, in raster block order,
+    /// passing each coefficient's position within its 8x8 block (`0..64`) and a mutable
+    /// reference to its value. `writable` controls whether mutations are persisted back to the
+    /// carrier; extraction passes `false` since it never writes a re-encoded image.
+    ///
+    /// Coefficients are only reachable a `v_samp_factor`-sized group of block rows at a time —
+    /// that's the window libjpeg's virtual array manager actually realizes in memory, mirroring
+    /// how `jpegtran`/`turbojpeg` walk the same arrays.
+    unsafe fn visit_luma_coefficients(
+        cinfo: &mut ffi::jpeg_decompress_struct,
+        coef_arrays: *mut *mut ffi::jvirt_barray_control,
+        writable: bool,
+        mut visit: impl FnMut(usize, &mut i16),
+    ) -> Result<(), DrmError> {
+        if cinfo.num_components < 1 {
+            return Err(DrmError::Message(
+                "JPEG carrier has no coefficient planes".to_string(),
+            ));
+        }
+        let comp = &*cinfo.comp_info;
+        let width_in_blocks = comp.width_in_blocks as usize;
+        let height_in_blocks = comp.height_in_blocks;
+        let v_samp_factor = comp.v_samp_factor as ffi::JDIMENSION;
+        let access_virt_barray = (*cinfo.common.mem)
+            .access_virt_barray
+            .expect("libjpeg's memory manager always provides access_virt_barray");
+
+        let mut start_row: ffi::JDIMENSION = 0;
+        while start_row < height_in_blocks {
+            let rows = access_virt_barray(
+                &mut cinfo.common,
+                *coef_arrays,
+                start_row,
+                v_samp_factor,
+                writable as ffi::boolean,
+            );
+            let rows_in_group = v_samp_factor.min(height_in_blocks - start_row);
+            for r in 0..rows_in_group {
+                let block_row = *rows.add(r as usize);
+                for bx in 0..width_in_blocks {
+                    let block: &mut ffi::JBLOCK = &mut *block_row.add(bx);
+                    for (k, coeff) in block.iter_mut().enumerate() {
+                        visit(k, coeff);
+                    }
+                }
+            }
+            start_row += v_samp_factor;
+        }
+        Ok(())
+    }
+
+    /// Reads the luma component's DCT coefficients out of `image_data`, feeding each one
+    /// through `visit` in raster block order. Read-only: the carrier bytes are never re-encoded.
+    pub(crate) fn read_luma(
+        image_data: &[u8],
+        mut visit: impl FnMut(usize, i16),
+    ) -> Result<(), DrmError> {
+        unsafe {
+            let mut err = error_mgr();
+            let mut cinfo: ffi::jpeg_decompress_struct = std::mem::zeroed();
+            cinfo.common.err = &mut err;
+            ffi::jpeg_create_decompress(&mut cinfo);
+
+            let result = catch_unwind(AssertUnwindSafe(|| -> Result<(), DrmError> {
+                ffi::jpeg_mem_src(&mut cinfo, image_data.as_ptr(), image_data.len() as c_ulong);
+                ffi::jpeg_read_header(&mut cinfo, 1);
+                let coef_arrays = ffi::jpeg_read_coefficients(&mut cinfo);
+                if coef_arrays.is_null() {
+                    return Err(DrmError::Message(
+                        "JPEG carrier has no coefficient planes".to_string(),
+                    ));
+                }
+                visit_luma_coefficients(&mut cinfo, coef_arrays, false, |k, coeff| {
+                    visit(k, *coeff)
+                })
+            }))
+            .unwrap_or_else(|panic| Err(panic_to_err(panic)));
+
+            if result.is_ok() {
+                ffi::jpeg_finish_decompress(&mut cinfo);
+            } else {
+                ffi::jpeg_abort_decompress(&mut cinfo);
+            }
+            ffi::jpeg_destroy_decompress(&mut cinfo);
+            result
+        }
+    }
+
+    /// Decodes `image_data`, rewrites each luma DCT coefficient via `mutate` (given its
+    /// position within its 8x8 block and current value, returning the new value), and
+    /// re-encodes the result with the source's quantization tables and sampling factors
+    /// preserved via `jpeg_copy_critical_parameters`.
+    pub(crate) fn rewrite_luma(
+        image_data: &[u8],
+        mut mutate: impl FnMut(usize, i16) -> i16,
+    ) -> Result<Vec<u8>, DrmError> {
+        unsafe {
+            let mut src_err = error_mgr();
+            let mut srcinfo: ffi::jpeg_decompress_struct = std::mem::zeroed();
+            srcinfo.common.err = &mut src_err;
+            ffi::jpeg_create_decompress(&mut srcinfo);
+
+            let mut dst_err = error_mgr();
+            let mut dstinfo: ffi::jpeg_compress_struct = std::mem::zeroed();
+            dstinfo.common.err = &mut dst_err;
+            ffi::jpeg_create_compress(&mut dstinfo);
+
+            let mut out_buf: *mut u8 = std::ptr::null_mut();
+            let mut out_size: c_ulong = 0;
+
+            let result = catch_unwind(AssertUnwindSafe(|| -> Result<Vec<u8>, DrmError> {
+                ffi::jpeg_mem_src(&mut srcinfo, image_data.as_ptr(), image_data.len() as c_ulong);
+                ffi::jpeg_read_header(&mut srcinfo, 1);
+                let coef_arrays = ffi::jpeg_read_coefficients(&mut srcinfo);
+                if coef_arrays.is_null() {
+                    return Err(DrmError::Message(
+                        "JPEG carrier has no coefficient planes".to_string(),
+                    ));
+                }
+                visit_luma_coefficients(&mut srcinfo, coef_arrays, true, |k, coeff| {
+                    *coeff = mutate(k, *coeff);
+                })?;
+
+                ffi::jpeg_mem_dest(&mut dstinfo, &mut out_buf, &mut out_size);
+                ffi::jpeg_copy_critical_parameters(&srcinfo, &mut dstinfo);
+                ffi::jpeg_write_coefficients(&mut dstinfo, coef_arrays);
+                ffi::jpeg_finish_compress(&mut dstinfo);
+
+                Ok(std::slice::from_raw_parts(out_buf, out_size as usize).to_vec())
+            }))
+            .unwrap_or_else(|panic| Err(panic_to_err(panic)));
+
+            if result.is_ok() {
+                ffi::jpeg_finish_decompress(&mut srcinfo);
+            } else {
+                ffi::jpeg_abort_decompress(&mut srcinfo);
+                ffi::jpeg_abort_compress(&mut dstinfo);
+            }
+            if !out_buf.is_null() {
+                libc::free(out_buf as *mut c_void);
+            }
+            ffi::jpeg_destroy_compress(&mut dstinfo);
+            ffi::jpeg_destroy_decompress(&mut srcinfo);
+
+            result
+        }
+    }
+}
+
+/// Embeds `data_to_embed` into a JPEG's quantized DCT coefficients rather than pixel space,
+/// so the watermark survives the carrier's own re-encode. Only the luma (first) component is
+/// used: it is present at full resolution in every JPEG and carries the bulk of AC energy.
+fn embed_in_jpeg<R: Read, W: Write>(
+    mut carrier: R,
+    data_to_embed: &[u8],
+    mut out: W,
+) -> Result<(), DrmError> {
+    let mut image_data = Vec::new();
+    carrier.read_to_end(&mut image_data).map_err(DrmError::Io)?;
+
+    let mut bit_iter = data_to_embed
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1));
+    let mut ran_out_of_space = false;
+
+    let encoded = jpeg_coeff::rewrite_luma(&image_data, |index_in_block, coeff| {
+        if ran_out_of_space || !is_eligible_ac_coefficient(index_in_block, coeff) {
+            return coeff;
+        }
+        match bit_iter.next() {
+            Some(bit) => (coeff & !1) | (bit as i16),
+            None => {
+                ran_out_of_space = true;
+                coeff
+            }
+        }
+    })?;
+    if bit_iter.next().is_some() {
+        return Err(DrmError::Message(
+            "JPEG carrier too small: not enough eligible AC coefficients for this payload"
+                .to_string(),
+        ));
+    }
+
+    out.write_all(&encoded).map_err(DrmError::Io)?;
+    Ok(())
+}
+
+fn extract_from_jpeg<R: Read>(mut carrier: R) -> Result<Vec<u8>, DrmError> {
+    let mut image_data = Vec::new();
+    carrier.read_to_end(&mut image_data).map_err(DrmError::Io)?;
+
+    let mut eligible_bits = Vec::new();
+    jpeg_coeff::read_luma(&image_data, |index_in_block, coeff| {
+        if is_eligible_ac_coefficient(index_in_block, coeff) {
+            eligible_bits.push((coeff & 1) as u8);
+        }
+    })?;
+    let total_eligible_bits = eligible_bits.len();
+    let mut bit_iter = eligible_bits.into_iter();
+
+    let mut len_bytes = [0u8; 8];
+    for byte in &mut len_bytes {
+        for j in 0..8 {
+            if let Some(bit) = bit_iter.next() {
+                *byte |= bit << j;
+            } else {
+                return Err(DrmError::Message(
+                    "Reached end of JPEG coefficients before reading payload length".to_string(),
+                ));
+            }
+        }
+    }
+    let payload_len = u64::from_be_bytes(len_bytes) as usize;
+
+    // `payload_len` comes straight off carrier-controlled coefficient LSBs, so a corrupted or
+    // adversarial JPEG can claim any `u64` value; validate it against the actual eligible-bit
+    // budget (with checked arithmetic, since `payload_len * 8` can overflow) before allocating,
+    // rather than letting a bogus length turn into a huge/invalid allocation.
+    let required_bits = required_bits_for_payload(payload_len)?;
+    if required_bits > total_eligible_bits {
+        return Err(DrmError::Message(
+            "Reached end of JPEG coefficients before reading full payload".to_string(),
+        ));
+    }
+
+    let mut payload = vec![0u8; payload_len];
+    for byte in payload.iter_mut().take(payload_len) {
+        for j in 0..8 {
+            if let Some(bit) = bit_iter.next() {
+                *byte |= bit << j;
+            } else {
+                return Err(DrmError::Message(
+                    "Reached end of JPEG coefficients before reading full payload".to_string(),
+                ));
+            }
+        }
+    }
+
+    Ok(payload)
+}
+
+fn extract_from_pdf<R: Read>(mut carrier: R) -> Result<Vec<u8>, DrmError> {
+    let mut pdf_data = Vec::new();
+    carrier.read_to_end(&mut pdf_data).map_err(DrmError::Io)?;
+    let doc = Document::load_mem(&pdf_data)?;
     let page_ids = doc.get_pages().values().cloned().collect::<Vec<ObjectId>>();
     for page_id in page_ids {
         let page_dict = doc.get_object(page_id)?.as_dict()?;
@@ -187,8 +1037,27 @@ fn extract_from_pdf(pdf_data: &[u8]) -> Result<Vec<u8>, DrmError> {
     ))
 }
 
-fn embed_in_pdf(pdf_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmError> {
-    let mut doc = Document::load_mem(pdf_data)?;
+/// Embeds `data_to_embed` into a PDF by drawing a near-transparent noise image carrying the
+/// payload bytes onto every page, via a shared `XObject` referenced from each page's content
+/// stream.
+///
+/// This does not use a worker pool: the content stream is the same handful of PDF operators
+/// for every page (draw the shared noise image through the transparent graphics state), so
+/// there is no per-page encoding work to parallelize — building it once up front and cloning
+/// the resulting bytes is strictly cheaper than re-running a pool over identical input. The
+/// per-page step that *does* vary (looking up or creating each page's `Resources`/`XObject`/
+/// `ExtGState` entries) mutates `doc: Document` directly, and `lopdf::Document` is not `Sync`,
+/// so that step stays sequential rather than being handed to `rayon`. If a future carrier
+/// format embeds page-varying content (e.g. splitting the payload across distinct per-page
+/// images), revisit this: that would be genuine per-page work worth pooling.
+pub fn embed_in_pdf<R: Read, W: Write>(
+    mut carrier: R,
+    data_to_embed: &[u8],
+    mut out: W,
+) -> Result<(), DrmError> {
+    let mut pdf_data = Vec::new();
+    carrier.read_to_end(&mut pdf_data).map_err(DrmError::Io)?;
+    let mut doc = Document::load_mem(&pdf_data)?;
 
     // 1. Create noise image
     let side = (data_to_embed.len() as f64).sqrt().ceil() as u32;
@@ -225,7 +1094,33 @@ fn embed_in_pdf(pdf_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmErr
     let gs_dict = Dictionary::from_iter(vec![(b"ca".to_vec(), Object::Real(0.01))]);
     let gs_id = doc.add_object(gs_dict);
 
-    // 4. Iterate over pages and add the image
+    // 4. The content stream is the same handful of operators for every page (draw the noise
+    // image through the transparent graphics state), so it's encoded once up front rather
+    // than once per page; there's no per-page work here to hand to the worker pool.
+    let content_ops = vec![
+        Operation::new("q", vec![]),
+        Operation::new("gs", vec![Object::Name(b"UpdrmGS".to_vec())]),
+        Operation::new(
+            "cm",
+            vec![
+                10.0.into(),
+                0.into(),
+                0.into(),
+                10.0.into(),
+                50.into(),
+                50.into(),
+            ],
+        ),
+        Operation::new("Do", vec![Object::Name(b"UpdrmImg".to_vec())]),
+        Operation::new("Q", vec![]),
+    ];
+    let content_stream_bytes = Content {
+        operations: content_ops,
+    }
+    .encode()?;
+
+    // 5. Apply every page's resources + content mutation. This stays sequential:
+    // `lopdf::Document` isn't `Sync`, so there's no safe way to mutate it from the pool.
     let page_ids = doc.get_pages().values().cloned().collect::<Vec<ObjectId>>();
     for page_id in page_ids {
         let resources_id = {
@@ -271,30 +1166,7 @@ fn embed_in_pdf(pdf_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmErr
             .as_dict_mut()?
             .set(b"UpdrmGS", gs_id);
 
-        let content_ops = vec![
-            Operation::new("q", vec![]),
-            Operation::new("gs", vec![Object::Name(b"UpdrmGS".to_vec())]),
-            Operation::new(
-                "cm",
-                vec![
-                    10.0.into(),
-                    0.into(),
-                    0.into(),
-                    10.0.into(),
-                    50.into(),
-                    50.into(),
-                ],
-            ),
-            Operation::new("Do", vec![Object::Name(b"UpdrmImg".to_vec())]),
-            Operation::new("Q", vec![]),
-        ];
-        let new_content_stream = Stream::new(
-            Dictionary::new(),
-            Content {
-                operations: content_ops,
-            }
-            .encode()?,
-        );
+        let new_content_stream = Stream::new(Dictionary::new(), content_stream_bytes.clone());
         let new_content_id = doc.add_object(new_content_stream);
 
         let page_dict = doc.get_object_mut(page_id)?.as_dict_mut()?;
@@ -307,85 +1179,131 @@ fn embed_in_pdf(pdf_data: &[u8], data_to_embed: &[u8]) -> Result<Vec<u8>, DrmErr
         page_dict.set(b"Contents", Object::Array(contents_array));
     }
 
-    let mut result_bytes = Vec::new();
-    doc.save_to(&mut result_bytes)?;
-    Ok(result_bytes)
+    doc.save_to(&mut out)?;
+    Ok(())
 }
 
-// ... (write and read functions are unchanged) ...
-#[pyfunction]
-fn write(py: Python, file_path: String, data: &Bound<'_, PyAny>) -> Result<(), DrmError> {
-    let _ = py;
-
-    let raw_data: Vec<u8> = if let Ok(text) = data.extract::<String>() {
-        text.into_bytes()
+fn extract_raw_data(data: &Bound<'_, PyAny>) -> Result<Vec<u8>, DrmError> {
+    if let Ok(text) = data.extract::<String>() {
+        Ok(text.into_bytes())
     } else if let Ok(bytes) = data.extract::<Vec<u8>>() {
-        bytes
+        Ok(bytes)
     } else {
-        return Err(DrmError::Py(
-            PyErr::new::<pyo3::exceptions::PyTypeError, _>(
-                "Data must be a string (JSON) or bytes (image)".to_string(),
-            ),
-        ));
-    };
+        Err(DrmError::Py(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+            "Data must be a string (JSON) or bytes (image)".to_string(),
+        )))
+    }
+}
 
-    let len_bytes = (raw_data.len() as u32).to_be_bytes();
-    let mut full_data = len_bytes.to_vec();
-    full_data.extend_from_slice(&raw_data);
-
-    const DATA_SHARDS: usize = 10;
-    const PARITY_SHARDS: usize = 4;
-    let rs = ReedSolomon::<galois_8::Field>::new(DATA_SHARDS, PARITY_SHARDS)?;
-    let shard_len = (full_data.len() + DATA_SHARDS - 1) / DATA_SHARDS;
-    let mut shards: Vec<Vec<u8>> = full_data.chunks(shard_len).map(|c| c.to_vec()).collect();
-    shards.iter_mut().for_each(|s| s.resize(shard_len, 0));
-    for _ in 0..PARITY_SHARDS {
-        shards.push(vec![0; shard_len]);
+/// Builds a rayon thread pool sized to `threads` (`0` means "let rayon pick", which defaults
+/// to all cores). This is a raw, uncached constructor — callers that just want *a* pool for
+/// the current `threads` setting should go through [`shard_pool_for`] instead, which reuses
+/// one pool per distinct `threads` value rather than spinning up a fresh OS thread pool on
+/// every call.
+pub fn build_thread_pool(threads: usize) -> Result<rayon::ThreadPool, DrmError> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if threads > 0 {
+        builder = builder.num_threads(threads);
     }
-    rs.encode(&mut shards)?;
-    let encoded_data: Vec<u8> = shards.into_iter().flatten().collect();
+    Ok(builder.build()?)
+}
 
-    let mut final_payload = (encoded_data.len() as u64).to_be_bytes().to_vec();
-    final_payload.extend_from_slice(&encoded_data);
+type ThreadPoolCache = Mutex<HashMap<usize, Arc<rayon::ThreadPool>>>;
 
-    let original_file_bytes = fs::read(&file_path)?;
+/// Process-wide cache of thread pools keyed by the `threads` setting that built them, so a
+/// sequence of small `write`/`write_bytes` calls (the common case: one JSON watermark into
+/// one PNG) doesn't pay OS thread-spawn/teardown cost on every single call.
+static SHARD_POOL_CACHE: OnceLock<ThreadPoolCache> = OnceLock::new();
 
-    let file_type = detect_file_type(&original_file_bytes);
+/// Returns the cached thread pool for `threads`, building and caching it on first use.
+fn shard_pool_for(threads: usize) -> Result<Arc<rayon::ThreadPool>, DrmError> {
+    let cache = SHARD_POOL_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap();
+    if let Some(pool) = cache.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = Arc::new(build_thread_pool(threads)?);
+    cache.insert(threads, pool.clone());
+    Ok(pool)
+}
 
-    let modified_file_bytes = match file_type {
-        FileType::Png => embed_in_png(&original_file_bytes, &final_payload)?,
-        FileType::Pdf => embed_in_pdf(&original_file_bytes, &final_payload)?,
-        FileType::Unsupported => {
-            return Err(DrmError::Message(
-                "Unsupported file type. Only PNG and PDF are supported.".to_string(),
-            ))
-        }
+pub fn encode_payload(
+    raw_data: Vec<u8>,
+    codec: Codec,
+    redundancy: Redundancy,
+    threads: usize,
+) -> Result<Vec<u8>, DrmError> {
+    let data_shards = redundancy.data_shards as usize;
+    let parity_shards = redundancy.parity_shards as usize;
+
+    let crc32 = crc32_of(&raw_data);
+    let compressed = codec.compress(&raw_data)?;
+
+    // The compressed length is carried alongside the shard data itself (rather than in the
+    // fixed header) because shard padding rounds the RS-encoded length up; read needs it to
+    // know where the real compressed stream ends before decompressing.
+    let mut shard_input = (compressed.len() as u64).to_be_bytes().to_vec();
+    shard_input.extend_from_slice(&compressed);
+
+    let rs = ReedSolomon::<galois_8::Field>::new(data_shards, parity_shards)?;
+    // `ReedSolomon::new` above returns an error for `data_shards == 0`, so the division below
+    // can't panic; `.max(1)` still guards the degenerate case of an empty `shard_input`.
+    let shard_len = shard_input.len().div_ceil(data_shards).max(1);
+
+    // Splitting `shard_input` into its column shards and padding each one is independent
+    // per-column work, so it runs on a cached pool (reused across calls with the same
+    // `threads` setting); the actual RS parity matrix-multiply is a single call into
+    // `reed_solomon_erasure`, which doesn't expose a parallel entry point.
+    let pool = shard_pool_for(threads)?;
+    let mut shards: Vec<Vec<u8>> = pool.install(|| {
+        shard_input
+            .par_chunks(shard_len)
+            .map(|c| {
+                let mut s = c.to_vec();
+                s.resize(shard_len, 0);
+                s
+            })
+            .collect()
+    });
+    while shards.len() < data_shards {
+        shards.push(vec![0; shard_len]);
+    }
+    for _ in 0..parity_shards {
+        shards.push(vec![0; shard_len]);
+    }
+    rs.encode(&mut shards)?;
+    let encoded_data: Vec<u8> = shards.into_iter().flatten().collect();
+
+    let header = ContainerHeader {
+        version: CONTAINER_VERSION,
+        flags: codec.flag_bits(),
+        data_shards: redundancy.data_shards,
+        parity_shards: redundancy.parity_shards,
+        plaintext_len: raw_data.len() as u64,
+        crc32,
     };
 
-    fs::write(&file_path, &modified_file_bytes)?;
+    let mut container = header.to_bytes().to_vec();
+    container.extend_from_slice(&encoded_data);
 
-    Ok(())
+    Ok(container)
 }
 
-#[pyfunction]
-fn read(py: Python, file_path: String) -> Result<PyObject, DrmError> {
-    let file_bytes = fs::read(&file_path)?;
-    let file_type = detect_file_type(&file_bytes);
+fn decode_payload(container: &[u8]) -> Result<Vec<u8>, DrmError> {
+    let header = ContainerHeader::from_bytes(container)?;
+    let encoded_data = &container[ContainerHeader::SIZE..];
 
-    let encoded_data = match file_type {
-        FileType::Png => extract_from_png(&file_bytes)?,
-        FileType::Pdf => extract_from_pdf(&file_bytes)?,
-        FileType::Unsupported => {
-            return Err(DrmError::Message(
-                "Unsupported file type for extraction.".to_string(),
-            ))
-        }
-    };
+    let data_shards = header.data_shards as usize;
+    let parity_shards = header.parity_shards as usize;
+    let total_shards = data_shards + parity_shards;
+    if total_shards == 0 || !encoded_data.len().is_multiple_of(total_shards) {
+        return Err(DrmError::Message(
+            "Corrupt container: shard layout does not match the embedded header".to_string(),
+        ));
+    }
 
-    const DATA_SHARDS: usize = 10;
-    const PARITY_SHARDS: usize = 4;
-    let rs = ReedSolomon::<galois_8::Field>::new(DATA_SHARDS, PARITY_SHARDS)?;
-    let shard_len = encoded_data.len() / (DATA_SHARDS + PARITY_SHARDS);
+    let rs = ReedSolomon::<galois_8::Field>::new(data_shards, parity_shards)?;
+    let shard_len = encoded_data.len() / total_shards;
     let mut shards: Vec<Option<Vec<u8>>> = encoded_data
         .chunks(shard_len)
         .map(|c| Some(c.to_vec()))
@@ -393,36 +1311,329 @@ fn read(py: Python, file_path: String) -> Result<PyObject, DrmError> {
 
     rs.reconstruct(&mut shards)?;
 
-    let full_data: Vec<u8> = shards
+    let shard_input: Vec<u8> = shards
         .into_iter()
-        .take(DATA_SHARDS)
-        .filter_map(|s| s)
+        .take(data_shards)
+        .flatten()
         .flatten()
         .collect();
 
-    if full_data.len() < 4 {
+    if shard_input.len() < 8 {
+        return Err(DrmError::Message(
+            "Reconstructed data is too short to contain the compressed-length prefix".to_string(),
+        ));
+    }
+    let compressed_len =
+        u64::from_be_bytes(shard_input[0..8].try_into().unwrap()) as usize;
+    if shard_input.len() < 8 + compressed_len {
+        return Err(DrmError::Message(
+            "Reconstructed data is shorter than its compressed-length prefix claims".to_string(),
+        ));
+    }
+    let compressed = &shard_input[8..8 + compressed_len];
+
+    let codec = Codec::from_flags(header.flags)?;
+    let mut plaintext = codec.decompress(compressed)?;
+
+    if plaintext.len() < header.plaintext_len as usize {
         return Err(DrmError::Message(
-            "Reconstructed data is too short to contain length header.".to_string(),
+            "Decompressed data is shorter than the length recorded in the header".to_string(),
         ));
     }
-    let len_bytes: [u8; 4] = full_data[0..4]
-        .try_into()
-        .map_err(|_| DrmError::Message("Failed to read data length from payload".to_string()))?;
-    let raw_data_len = u32::from_be_bytes(len_bytes) as usize;
+    plaintext.truncate(header.plaintext_len as usize);
 
-    if full_data.len() < 4 + raw_data_len {
+    if crc32_of(&plaintext) != header.crc32 {
         return Err(DrmError::Message(
-            "Reconstructed data is shorter than specified by its length header.".to_string(),
+            "CRC32 mismatch: payload is corrupted or was truncated".to_string(),
         ));
     }
-    let raw_data = &full_data[4..(4 + raw_data_len)];
 
-    Ok(PyBytes::new_bound(py, raw_data).into())
+    Ok(plaintext)
+}
+
+/// Embeds `data` into `carrier_bytes`, writing the resulting carrier to `out`.
+///
+/// This is the shared core behind both the file-path and in-memory APIs: it runs the same
+/// `detect_file_type` + `embed_in_*` logic regardless of whether the carrier came from disk
+/// or from a caller-supplied buffer. `redundancy` picks the RS shard ratio (see
+/// [`Redundancy`]); `threads` sizes the (cached, see [`shard_pool_for`]) worker pool used for
+/// RS shard preparation; `0` means "use all cores".
+fn embed_payload<W: Write>(
+    carrier_bytes: &[u8],
+    data: &Bound<'_, PyAny>,
+    codec: Codec,
+    redundancy: Redundancy,
+    threads: usize,
+    out: W,
+) -> Result<(), DrmError> {
+    let raw_data = extract_raw_data(data)?;
+    let container = encode_payload(raw_data, codec, redundancy, threads)?;
+
+    // Carriers extract a bare bitstream with no framing of their own, so every `embed_in_*`
+    // expects its input pre-fixed with an 8-byte big-endian length it can use to know where
+    // the payload ends; `extract_from_*` strips that same prefix on the way out.
+    let mut length_prefixed = (container.len() as u64).to_be_bytes().to_vec();
+    length_prefixed.extend_from_slice(&container);
+
+    match detect_file_type(carrier_bytes) {
+        FileType::Png => embed_in_png(carrier_bytes, &length_prefixed, out),
+        FileType::Pdf => embed_in_pdf(carrier_bytes, &length_prefixed, out),
+        FileType::Jpeg => embed_in_jpeg(carrier_bytes, &length_prefixed, out),
+        FileType::Unsupported => Err(DrmError::Message(
+            "Unsupported file type. Only PNG, PDF and JPEG are supported.".to_string(),
+        )),
+    }
+}
+
+/// Extracts the watermark payload from `carrier_bytes`.
+///
+/// Shared core behind both the file-path and in-memory APIs; see [`embed_payload`].
+fn extract_payload(carrier_bytes: &[u8]) -> Result<Vec<u8>, DrmError> {
+    let container = match detect_file_type(carrier_bytes) {
+        FileType::Png => extract_from_png(carrier_bytes)?,
+        FileType::Pdf => extract_from_pdf(carrier_bytes)?,
+        FileType::Jpeg => extract_from_jpeg(carrier_bytes)?,
+        FileType::Unsupported => {
+            return Err(DrmError::Message(
+                "Unsupported file type for extraction.".to_string(),
+            ))
+        }
+    };
+
+    decode_payload(&container)
+}
+
+/// `redundancy` is either a profile name (`"light"`, `"standard"`, `"paranoid"`) or an
+/// explicit `(data_shards, parity_shards)` pair; see [`Redundancy`]. `threads` sizes the
+/// worker pool used for RS shard preparation; `0` (the default) means "use all cores".
+#[pyfunction]
+#[pyo3(signature = (file_path, data, codec=None, redundancy=None, threads=0))]
+fn write(
+    py: Python,
+    file_path: String,
+    data: &Bound<'_, PyAny>,
+    codec: Option<String>,
+    redundancy: Option<&Bound<'_, PyAny>>,
+    threads: usize,
+) -> Result<(), DrmError> {
+    let _ = py;
+
+    let codec = Codec::parse(codec.as_deref())?;
+    let redundancy = Redundancy::parse(redundancy)?;
+    let carrier_bytes = fs::read(&file_path)?;
+    let mut modified_file_bytes = Vec::new();
+    embed_payload(
+        &carrier_bytes,
+        data,
+        codec,
+        redundancy,
+        threads,
+        &mut modified_file_bytes,
+    )?;
+    fs::write(&file_path, &modified_file_bytes)?;
+
+    Ok(())
+}
+
+#[pyfunction]
+fn read(py: Python, file_path: String) -> Result<PyObject, DrmError> {
+    let carrier_bytes = fs::read(&file_path)?;
+    let raw_data = extract_payload(&carrier_bytes)?;
+
+    Ok(PyBytes::new_bound(py, &raw_data).into())
+}
+
+/// In-memory counterpart to [`write`]: embeds `data` into `carrier_bytes` and returns the
+/// modified carrier, without touching disk. Useful for pipelines that already hold the
+/// carrier in memory (network uploads, web handlers, ...). See [`write`] for `redundancy`
+/// and `threads`.
+#[pyfunction]
+#[pyo3(signature = (carrier_bytes, data, codec=None, redundancy=None, threads=0))]
+fn write_bytes(
+    py: Python,
+    carrier_bytes: &[u8],
+    data: &Bound<'_, PyAny>,
+    codec: Option<String>,
+    redundancy: Option<&Bound<'_, PyAny>>,
+    threads: usize,
+) -> Result<PyObject, DrmError> {
+    let codec = Codec::parse(codec.as_deref())?;
+    let redundancy = Redundancy::parse(redundancy)?;
+    let mut modified_bytes = Vec::new();
+    embed_payload(
+        carrier_bytes,
+        data,
+        codec,
+        redundancy,
+        threads,
+        &mut modified_bytes,
+    )?;
+
+    Ok(PyBytes::new_bound(py, &modified_bytes).into())
+}
+
+/// In-memory counterpart to [`read`]: extracts the watermark payload from `carrier_bytes`
+/// without touching disk.
+#[pyfunction]
+fn read_bytes(py: Python, carrier_bytes: &[u8]) -> Result<PyObject, DrmError> {
+    let raw_data = extract_payload(carrier_bytes)?;
+
+    Ok(PyBytes::new_bound(py, &raw_data).into())
 }
 
 #[pymodule]
 fn mirseo_updrm(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(write, m)?)?;
     m.add_function(wrap_pyfunction!(read, m)?)?;
+    m.add_function(wrap_pyfunction!(write_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(read_bytes, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip_uncompressed() {
+        let raw_data = b"the quick brown fox jumps over the lazy dog".to_vec();
+        let container =
+            encode_payload(raw_data.clone(), Codec::None, Redundancy::default(), 0).unwrap();
+        let recovered = decode_payload(&container).unwrap();
+        assert_eq!(recovered, raw_data);
+    }
+
+    #[test]
+    fn encode_decode_round_trip_compressed() {
+        let raw_data = vec![b'a'; 4096];
+        let container =
+            encode_payload(raw_data.clone(), Codec::Deflate, Redundancy::default(), 0).unwrap();
+        let recovered = decode_payload(&container).unwrap();
+        assert_eq!(recovered, raw_data);
+    }
+
+    #[test]
+    fn decode_rejects_corrupted_container_on_crc_mismatch() {
+        let raw_data = b"watermark payload".to_vec();
+        let mut container =
+            encode_payload(raw_data, Codec::None, Redundancy::default(), 0).unwrap();
+
+        // Flip a byte past the fixed header *and* the 8-byte compressed-length prefix, landing
+        // inside the actual payload bytes: Reed-Solomon reconstruct() only fills in shards
+        // explicitly marked missing, so a bit flip in a shard that's still present survives
+        // reconstruction untouched and the recovered plaintext no longer matches the CRC32
+        // recorded in the header.
+        let corrupt_at = ContainerHeader::SIZE + 8;
+        container[corrupt_at] ^= 0xFF;
+
+        match decode_payload(&container) {
+            Err(DrmError::Message(msg)) => assert!(msg.contains("CRC32 mismatch")),
+            other => panic!("expected a CRC32 mismatch error, got {other:?}"),
+        }
+    }
+
+    /// `embed_in_jpeg`/`embed_in_apng`/`embed_in_png` expect their input already framed with an
+    /// 8-byte big-endian length prefix — see the comment inside [`embed_payload`] — which is normally
+    /// added by that caller. Tests exercising the carrier functions directly need to add it too.
+    fn length_prefixed(payload: &[u8]) -> Vec<u8> {
+        let mut framed = (payload.len() as u64).to_be_bytes().to_vec();
+        framed.extend_from_slice(payload);
+        framed
+    }
+
+    /// Builds a synthetic baseline JPEG with enough high-frequency detail that its luma AC
+    /// coefficients aren't all quantized to 0/1/-1 (which `is_eligible_ac_coefficient` would
+    /// reject, starving the carrier of capacity).
+    fn build_test_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
+            let v = ((x * 37 + y * 91) % 256) as u8;
+            Rgba([v, 255u8.wrapping_sub(v), (v / 2).wrapping_add(64), 255])
+        });
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn jpeg_embed_extract_round_trip() {
+        let carrier = build_test_jpeg(128, 128);
+        let payload = b"jpeg watermark payload".to_vec();
+
+        let mut embedded = Vec::new();
+        embed_in_jpeg(carrier.as_slice(), &length_prefixed(&payload), &mut embedded).unwrap();
+        let recovered = extract_from_jpeg(embedded.as_slice()).unwrap();
+
+        assert_eq!(recovered, payload);
+    }
+
+    #[test]
+    fn jpeg_embed_rejects_payload_too_large_for_carrier() {
+        let carrier = build_test_jpeg(8, 8);
+        let payload = vec![0xAB; 4096];
+
+        let mut embedded = Vec::new();
+        match embed_in_jpeg(carrier.as_slice(), &length_prefixed(&payload), &mut embedded) {
+            Err(DrmError::Message(msg)) => assert!(msg.contains("too small")),
+            other => panic!("expected a carrier-too-small error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn jpeg_extract_rejects_malformed_carrier() {
+        let garbage = b"not a jpeg file".to_vec();
+        assert!(extract_from_jpeg(garbage.as_slice()).is_err());
+    }
+
+    /// Builds a synthetic APNG with `frame_count` solid-but-distinct RGBA frames, which is all
+    /// `decode_apng_frames` needs: it only cares about frame boundaries and pixel bytes, not
+    /// visual content.
+    fn build_test_apng(width: u32, height: u32, frame_count: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frame_count, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for frame_index in 0..frame_count {
+            let fill = 0x10u8.wrapping_add((frame_index * 0x20) as u8);
+            let frame = vec![fill; (width * height * 4) as usize];
+            writer.set_frame_delay(1, 30).unwrap();
+            writer.set_dispose_op(png::DisposeOp::None).unwrap();
+            writer.set_blend_op(png::BlendOp::Source).unwrap();
+            writer.write_image_data(&frame).unwrap();
+        }
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn apng_embed_extract_round_trip() {
+        let carrier = build_test_apng(16, 16, 3);
+        let payload = b"apng watermark payload".to_vec();
+
+        let mut embedded = Vec::new();
+        embed_in_apng(carrier.as_slice(), &length_prefixed(&payload), &mut embedded).unwrap();
+        let recovered = extract_from_apng(embedded.as_slice()).unwrap();
+
+        assert_eq!(recovered, payload);
+    }
+
+    #[test]
+    fn apng_embed_rejects_payload_too_large_for_carrier() {
+        let carrier = build_test_apng(2, 2, 1);
+        let payload = vec![0xCD; 4096];
+
+        let mut embedded = Vec::new();
+        match embed_in_apng(carrier.as_slice(), &length_prefixed(&payload), &mut embedded) {
+            Err(DrmError::Message(msg)) => assert!(msg.contains("Not enough space")),
+            other => panic!("expected a not-enough-space error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn apng_extract_rejects_malformed_carrier() {
+        let garbage = b"not a png file".to_vec();
+        assert!(extract_from_apng(garbage.as_slice()).is_err());
+    }
+}